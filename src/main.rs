@@ -8,6 +8,7 @@ use std::io;
 use std::io::{Read, Write};
 use std::fs;
 use std::cmp;
+use std::collections::HashMap;
 use rand::Rng;
 
 // -----------------------------------------------------------------------------------------------
@@ -46,12 +47,21 @@ trait Compressor {
     fn decompress<'a>(&'a mut self, data: &'a [u8]) -> &'a [u8];
 }
 
+// Every codec that can appear in a `BlockCompressor` stream is tagged with a stable id byte, which
+// is written alongside each block so a `BlockDecompressor` can pick the right codec out of a
+// `CompressorList` without the caller having to remember which one produced the file. Kept as its
+// own trait rather than folded into `Compressor` because an associated const isn't dyn compatible,
+// and `CompressorList` needs to store codecs as `Box<Compressor>`.
+trait CompressorId: Compressor {
+    const ID: u8;
+}
+
 // -----------------------------------------------------------------------------------------------
-struct NoCompression; 
+struct NoCompression;
 
 impl NoCompression {
     fn new() -> NoCompression {
-        NoCompression 
+        NoCompression
     }
 }
 
@@ -65,6 +75,10 @@ impl Compressor for NoCompression {
     }
 }
 
+impl CompressorId for NoCompression {
+    const ID: u8 = 0;
+}
+
 // -----------------------------------------------------------------------------------------------
 struct SnappyCompressor {
     buffer: Vec<u8>
@@ -90,16 +104,587 @@ impl Compressor for SnappyCompressor {
     }
 }
 
+impl CompressorId for SnappyCompressor {
+    const ID: u8 = 1;
+}
+
+// -----------------------------------------------------------------------------------------------
+const FSST_ESCAPE: u8 = 255;
+const FSST_MAX_SYMBOLS: usize = 255;
+const FSST_MAX_SYMBOL_LENGTH: usize = 8;
+
+#[derive(Clone)]
+struct FsstSymbol {
+    bytes: Vec<u8>
+}
+
+// A Fast Static Symbol Table codec: a trained table of up to 255 short byte strings is assigned
+// 1-byte codes, and the data is rewritten as a sequence of codes (or an escaped literal byte for
+// anything the table doesn't cover). Short, repetitive records compress better under this than
+// under a general-purpose byte compressor like Snappy. The trait is stateless across calls, so the
+// trained table is serialized into the block header and rebuilt by `decompress`.
+struct FsstCompressor {
+    table: Vec<FsstSymbol>,
+    hash_table: HashMap<u64, usize>,
+    buffer: Vec<u8>
+}
+
+impl FsstCompressor {
+    fn new() -> FsstCompressor {
+        FsstCompressor {
+            table: Vec::new(),
+            hash_table: HashMap::new(),
+            buffer: Vec::new()
+        }
+    }
+
+    fn hash_bytes(bytes: &[u8]) -> u64 {
+        let mut h: u64 = 0xcbf29ce484222325;
+        for &b in bytes {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        h
+    }
+
+    fn build_hash_table(&mut self) {
+        self.hash_table.clear();
+
+        // Insert shorter symbols first so that, on a prefix collision, the longer symbol wins and
+        // is tried first by `find_match` (a full compare still guards against a false hit).
+        let mut order: Vec<usize> = (0..self.table.len()).collect();
+        order.sort_by_key(|&i| self.table[i].bytes.len());
+
+        for idx in order {
+            let prefix_len = cmp::min(self.table[idx].bytes.len(), 3);
+            let h = FsstCompressor::hash_bytes(&self.table[idx].bytes[..prefix_len]);
+            self.hash_table.insert(h, idx);
+        }
+    }
+
+    // Lossy match: look up a short prefix hash and verify the candidate with a full compare.
+    fn find_match(&self, data: &[u8]) -> Option<usize> {
+        for &prefix_len in &[3usize, 2, 1] {
+            if data.len() >= prefix_len {
+                let h = FsstCompressor::hash_bytes(&data[..prefix_len]);
+                if let Some(&idx) = self.hash_table.get(&h) {
+                    let symbol = &self.table[idx].bytes;
+                    if data.len() >= symbol.len() && &data[..symbol.len()] == &symbol[..] {
+                        return Some(idx);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Iteratively trains a fresh table over the given sample blocks: each round re-encodes the
+    // samples with the current table, scores every emitted symbol and every adjacent symbol pair
+    // by `frequency * length`, and keeps the top `FSST_MAX_SYMBOLS` candidates for the next round.
+    fn train_bulk(&mut self, samples: &[&[u8]]) {
+        self.table.clear();
+        self.build_hash_table();
+
+        for _round in 0..5 {
+            let mut freq: HashMap<Vec<u8>, usize> = HashMap::new();
+
+            for sample in samples {
+                let mut pos = 0;
+                let mut prev_symbol: Option<Vec<u8>> = None;
+
+                while pos < sample.len() {
+                    let (symbol_bytes, advance) = match self.find_match(&sample[pos..]) {
+                        Some(idx) => (self.table[idx].bytes.clone(), self.table[idx].bytes.len()),
+                        None => (vec![sample[pos]], 1)
+                    };
+
+                    *freq.entry(symbol_bytes.clone()).or_insert(0) += 1;
+
+                    if let Some(prev) = prev_symbol.take() {
+                        let mut pair = prev;
+                        pair.extend_from_slice(&symbol_bytes);
+                        pair.truncate(FSST_MAX_SYMBOL_LENGTH);
+                        *freq.entry(pair).or_insert(0) += 1;
+                    }
+
+                    prev_symbol = Some(symbol_bytes);
+                    pos += advance;
+                }
+            }
+
+            let mut candidates: Vec<(Vec<u8>, usize)> = freq.into_iter().collect();
+            candidates.sort_by(|a, b| (b.1 * b.0.len()).cmp(&(a.1 * a.0.len())));
+            candidates.truncate(FSST_MAX_SYMBOLS);
+
+            self.table = candidates.into_iter().map(|(bytes, _)| FsstSymbol { bytes: bytes }).collect();
+            self.build_hash_table();
+        }
+    }
+
+    fn train(&mut self, sample: &[u8]) {
+        self.train_bulk(&[sample]);
+    }
+}
+
+impl Compressor for FsstCompressor {
+    fn compress<'a>(&'a mut self, data: &'a [u8]) -> &'a [u8] {
+        if self.table.is_empty() {
+            self.train(data);
+        }
+
+        self.buffer.clear();
+        self.buffer.push(self.table.len() as u8);
+        for symbol in &self.table {
+            self.buffer.push(symbol.bytes.len() as u8);
+            self.buffer.extend_from_slice(&symbol.bytes);
+        }
+
+        let mut pos = 0;
+        while pos < data.len() {
+            match self.find_match(&data[pos..]) {
+                Some(idx) => {
+                    self.buffer.push(idx as u8);
+                    pos += self.table[idx].bytes.len();
+                }
+                None => {
+                    self.buffer.push(FSST_ESCAPE);
+                    self.buffer.push(data[pos]);
+                    pos += 1;
+                }
+            }
+        }
+
+        &self.buffer[..]
+    }
+
+    fn decompress<'a>(&'a mut self, data: &'a [u8]) -> &'a [u8] {
+        if data.is_empty() {
+            self.buffer.clear();
+            return &self.buffer[..];
+        }
+
+        let table_len = data[0] as usize;
+        let mut offset = 1;
+        let mut table: Vec<&[u8]> = Vec::with_capacity(table_len);
+        for _ in 0..table_len {
+            let len = data[offset] as usize;
+            offset += 1;
+            table.push(&data[offset..offset+len]);
+            offset += len;
+        }
+
+        self.buffer.clear();
+        let mut pos = offset;
+        while pos < data.len() {
+            let code = data[pos];
+            pos += 1;
+            if code == FSST_ESCAPE {
+                self.buffer.push(data[pos]);
+                pos += 1;
+            } else {
+                self.buffer.extend_from_slice(table[code as usize]);
+            }
+        }
+
+        &self.buffer[..]
+    }
+}
+
+impl CompressorId for FsstCompressor {
+    const ID: u8 = 2;
+}
+
 // -----------------------------------------------------------------------------------------------
-struct BlockCompressor<C> 
-    where C: Compressor
+fn stream_vbyte_width(value: u64) -> usize {
+    if value < (1 << 8) { 1 }
+    else if value < (1 << 16) { 2 }
+    else if value < (1 << 24) { 3 }
+    else if value < (1 << 32) { 4 }
+    else if value < (1 << 40) { 5 }
+    else if value < (1 << 48) { 6 }
+    else if value < (1 << 56) { 7 }
+    else { 8 }
+}
+
+fn push_le_bytes(data: &mut Vec<u8>, value: u64, width: usize) {
+    for i in 0..width {
+        data.push(((value >> (8*i)) & 0xff) as u8);
+    }
+}
+
+fn read_le_bytes(data: &[u8], width: usize) -> u64 {
+    let mut value: u64 = 0;
+    for i in 0..width {
+        value |= (data[i] as u64) << (8*i);
+    }
+    value
+}
+
+// Bridges the delta/zigzag math across integer widths: `IntegerCompressor<T>` needs to know how
+// many bits wide `T` is to shift a delta into its sign bit and back, but everything else
+// (StreamVByte packing, control-nibble layout) is identical regardless of width.
+trait ZigzagInt: Copy {
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn to_zigzag(self) -> u64;
+    fn from_zigzag(z: u64) -> Self;
+    fn zero() -> Self;
+}
+
+impl ZigzagInt for i32 {
+    fn wrapping_sub(self, other: i32) -> i32 { i32::wrapping_sub(self, other) }
+    fn wrapping_add(self, other: i32) -> i32 { i32::wrapping_add(self, other) }
+
+    fn to_zigzag(self) -> u64 {
+        (((self << 1) ^ (self >> 31)) as u32) as u64
+    }
+
+    fn from_zigzag(z: u64) -> i32 {
+        let z = z as u32;
+        ((z >> 1) as i32) ^ -((z & 1) as i32)
+    }
+
+    fn zero() -> i32 { 0 }
+}
+
+impl ZigzagInt for i64 {
+    fn wrapping_sub(self, other: i64) -> i64 { i64::wrapping_sub(self, other) }
+    fn wrapping_add(self, other: i64) -> i64 { i64::wrapping_add(self, other) }
+
+    fn to_zigzag(self) -> u64 {
+        ((self << 1) ^ (self >> 63)) as u64
+    }
+
+    fn from_zigzag(z: u64) -> i64 {
+        ((z >> 1) as i64) ^ -((z & 1) as i64)
+    }
+
+    fn zero() -> i64 { 0 }
+}
+
+// Delta-encodes a column of `T`s against the previous value, zigzag-maps the signed deltas to
+// unsigned so small magnitudes (in either direction) stay small, then packs them with StreamVByte:
+// a 4-bit length code per value (1-8 bytes) in a control stream, with the significant bytes
+// themselves in a separate data stream. This captures the structure of a sorted-ish integer column
+// far better than reinterpreting it as raw bytes and handing it to a general byte compressor.
+//
+// Implements `Compressor` by reinterpreting the byte block as `&[T]`, so it can be used as an
+// alternative front-end to `BlockCompressor` alongside the raw-bytes-into-Snappy path, for either
+// `i32` or `i64` columns.
+struct IntegerCompressor<T> {
+    compressed: Vec<u8>,
+    decompressed: Vec<T>
+}
+
+impl<T: ZigzagInt> IntegerCompressor<T> {
+    fn new() -> IntegerCompressor<T> {
+        IntegerCompressor {
+            compressed: Vec::new(),
+            decompressed: Vec::new()
+        }
+    }
+
+    fn compress_values(&mut self, values: &[T]) -> &[u8] {
+        self.compressed.clear();
+        self.compressed.extend_from_slice(values.len().to_raw_bytes());
+
+        let mut prev = T::zero();
+        let zigzags: Vec<u64> = values.iter().map(|&v| {
+            let delta = v.wrapping_sub(prev);
+            prev = v;
+            delta.to_zigzag()
+        }).collect();
+
+        let control_len = (zigzags.len() + 1) / 2;
+        let mut control = vec![0u8; control_len];
+        let mut data = Vec::new();
+
+        for (i, &z) in zigzags.iter().enumerate() {
+            let width = stream_vbyte_width(z);
+            control[i/2] |= ((width - 1) as u8) << ((i%2) * 4);
+            push_le_bytes(&mut data, z, width);
+        }
+
+        self.compressed.extend_from_slice(&control);
+        self.compressed.extend_from_slice(&data);
+
+        &self.compressed[..]
+    }
+
+    fn decompress_values(&mut self, data: &[u8]) -> &[T] {
+        let mut count: usize = 0;
+        {
+            let count_slice = count.to_raw_bytes_mut();
+            count_slice.copy_from_slice(&data[..count_slice.len()]);
+        }
+        let mut offset = mem::size_of::<usize>();
+
+        let control_len = (count + 1) / 2;
+        let control = &data[offset..offset+control_len];
+        offset += control_len;
+
+        self.decompressed.clear();
+        self.decompressed.reserve(count);
+
+        let mut prev = T::zero();
+        let mut pos = offset;
+        for i in 0..count {
+            let width = (((control[i/2] >> ((i%2) * 4)) & 0b1111) + 1) as usize;
+            let z = read_le_bytes(&data[pos..], width);
+            pos += width;
+
+            let delta = T::from_zigzag(z);
+            prev = prev.wrapping_add(delta);
+            self.decompressed.push(prev);
+        }
+
+        &self.decompressed[..]
+    }
+}
+
+impl<T: ZigzagInt> Compressor for IntegerCompressor<T> {
+    fn compress<'a>(&'a mut self, data: &'a [u8]) -> &'a [u8] {
+        let values = unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const T, data.len() / mem::size_of::<T>())
+        };
+        self.compress_values(values)
+    }
+
+    fn decompress<'a>(&'a mut self, data: &'a [u8]) -> &'a [u8] {
+        let values = self.decompress_values(data);
+        unsafe {
+            slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * mem::size_of::<T>())
+        }
+    }
+}
+
+impl CompressorId for IntegerCompressor<i32> {
+    const ID: u8 = 3;
+}
+
+impl CompressorId for IntegerCompressor<i64> {
+    const ID: u8 = 4;
+}
+
+// -----------------------------------------------------------------------------------------------
+// Random constants for the FastCDC rolling "gear" hash. Indexed by the low 6 bits of each byte, so
+// the table only needs 64 entries instead of the usual 256.
+const GEAR: [u64; 64] = [
+    0x1c80317fa3b1799d, 0xbdd640fb06671ad1, 0x3eb13b9046685257, 0x23b8c1e9392456de,
+    0x1a3d1fa7bc8960a9, 0xbd9c66b3ad3c2d6d, 0x8b9d2434e465e150, 0x972a846916419f82,
+    0x0822e8f36c031199, 0x17fc695a07a0ca6e, 0x3b8faa1837f8a88b, 0x9a1de644815ef6d1,
+    0x8fadc1a606cb0fb3, 0xb74d0fb132e70629, 0xb38a088ca65ed389, 0x6b65a6a48b8148f6,
+    0x72ff5d2a386ecbe0, 0x4737819096da1dac, 0xde8a774bcf36d58b, 0xc241330b01a9e71f,
+    0x28df6ec4ce4a2bbd, 0x6c307511b2b9437a, 0x47229389571aa876, 0x371ecd7b27cd8130,
+    0xc37459eef50bea63, 0x1a2a73ed562b0f79, 0x6142ea7d17be3111, 0x5be6128e18c26797,
+    0x580d7b71d8f56413, 0x43b7a3a69a8dca03, 0x0b1f9163ce9ff57f, 0x759cde66bacfb3d0,
+    0x1ff49b7889463e85, 0xec1b8ca1f91e1d4c, 0x142c3fe860e7a113, 0x4b0dbb418d5288f1,
+    0xa0ee89aed453dd32, 0xe2acf72f9e574f7a, 0x5c941cf0dc98d2c1, 0x3139d32c93cd59bf,
+    0x11ce5dd2b45ed1f0, 0xa9488d990bbb2599, 0xc5e7ce8a3a578a8e, 0xfc377a4c4a15544d,
+    0xdaf61a26146d3f31, 0xddd1dfb23b982ef8, 0x614ff3d719db3ad0, 0x7412b29347294739,
+    0xd58842dea2bc372f, 0x29a3b2e95d65a441, 0x5af305535ec42e08, 0xab9099a435a240ae,
+    0xb3aa7efe4458a885, 0xaefcfad8efc89849, 0x12476f57a5e5a5ab, 0xa28defe39bf00273,
+    0x88bd64072bcfbe01, 0x3eabedcbbaa80dd4, 0x7656af7229d4beef, 0x451b4cf36123fdf7,
+    0xece66fa2fd5166e6, 0xb02b61c4a3d70628, 0x3838b3268e944239, 0x5304317faf42e12f,
+];
+
+fn ones_mask(bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else if bits >= 64 {
+        !0u64
+    } else {
+        (1u64 << bits) - 1
+    }
+}
+
+// Places `BlockCompressor` boundaries by content instead of at fixed offsets, so inserting bytes
+// near the start of a stream only shifts the chunk it falls in rather than every chunk after it.
+// Uses FastCDC's normalized chunking: a stricter mask (more one-bits, less likely to match) is used
+// below the average target size to discourage very short chunks, and a looser mask is used above it
+// to encourage cutting before the hard maximum.
+struct FastCdcChunker {
+    avg_size: usize,
+    min_size: usize,
+    max_size: usize,
+    mask_s: u64,
+    mask_l: u64
+}
+
+impl FastCdcChunker {
+    fn new(avg_size: usize, min_size: usize, max_size: usize) -> FastCdcChunker {
+        let bits = (avg_size as f64).log2().round() as u32;
+        FastCdcChunker {
+            avg_size: avg_size,
+            min_size: min_size,
+            max_size: max_size,
+            mask_s: ones_mask(bits + 1),
+            mask_l: ones_mask(bits.saturating_sub(1))
+        }
+    }
+
+    // Returns the next content-defined chunk from the front of `data`.
+    fn next_chunk<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let len = data.len();
+        if len <= self.min_size {
+            return data;
+        }
+
+        let max_size = cmp::min(self.max_size, len);
+        let mut h: u64 = 0;
+        let mut i = self.min_size;
+
+        while i < max_size {
+            h = (h << 1).wrapping_add(GEAR[(data[i] as usize) & 0x3f]);
+            let mask = if i < self.avg_size { self.mask_s } else { self.mask_l };
+            if h & mask == 0 {
+                return &data[..i+1];
+            }
+            i += 1;
+        }
+
+        &data[..max_size]
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// A registry mapping codec ids to the boxed codec that understands them, used on the read side so
+// a single stream can mix blocks produced by different `Compressor` implementations.
+struct CompressorList {
+    compressors: HashMap<u8, Box<Compressor>>
+}
+
+impl CompressorList {
+    fn new() -> CompressorList {
+        CompressorList {
+            compressors: HashMap::new()
+        }
+    }
+
+    fn register(&mut self, id: u8, compressor: Box<Compressor>) {
+        self.compressors.insert(id, compressor);
+    }
+
+    fn get_mut(&mut self, id: u8) -> Option<&mut Box<Compressor>> {
+        self.compressors.get_mut(&id)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+const STREAM_MAGIC: u32 = 0x5a435031; // "1PCZ" little-endian
+const STREAM_VERSION: u8 = 1;
+
+fn crc32c(data: &[u8]) -> u32 {
+    let poly: u32 = 0x82f63b78;
+    let mut crc: u32 = 0xffffffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn write_u32(dest: &mut io::Write, value: u32) -> io::Result<()> {
+    let bytes = [
+        (value & 0xff) as u8,
+        ((value >> 8) & 0xff) as u8,
+        ((value >> 16) & 0xff) as u8,
+        ((value >> 24) & 0xff) as u8,
+    ];
+    dest.write_all(&bytes)
+}
+
+fn read_u32(reader: &mut Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok((bytes[0] as u32) | ((bytes[1] as u32) << 8) | ((bytes[2] as u32) << 16) | ((bytes[3] as u32) << 24))
+}
+
+// Errors a `BlockDecompressor` can hit while reading a stream. Corruption and unsupported formats
+// are reported here rather than panicking, so a truncated or foreign file is a recoverable Err
+// instead of taking down the reader.
+#[derive(Debug)]
+enum BlockError {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnknownCodec(u8),
+    CrcMismatch,
+    TruncatedStream
+}
+
+impl From<io::Error> for BlockError {
+    fn from(err: io::Error) -> BlockError {
+        BlockError::Io(err)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// Writes the shared stream header (magic + version) by itself, so a caller that wants to mix
+// codecs within one stream can write it exactly once and then call `append_blocks`/
+// `append_blocks_cdc` any number of times afterward, each with whichever `CompressorId` fits that
+// segment. `BlockCompressor::compress`/`compress_cdc` below are just the common single-codec case
+// built on top of this.
+fn write_stream_header(dest: &mut io::Write) -> io::Result<()> {
+    write_u32(dest, STREAM_MAGIC)?;
+    dest.write_all(&[STREAM_VERSION])
+}
+
+fn write_frame(dest: &mut io::Write, codec_id: u8, compressed_chunk: &[u8]) -> io::Result<()> {
+    dest.write_all(&[codec_id])?;
+    dest.write_all(compressed_chunk.len().to_raw_bytes())?;
+    write_u32(dest, crc32c(compressed_chunk))?;
+    dest.write_all(compressed_chunk)
+}
+
+// Splits `data` into fixed-size blocks and appends one framed block per chunk using `compressor`.
+// Assumes a stream header was already written by `write_stream_header` (possibly followed by
+// earlier calls to this function with a different compressor) -- it never writes one itself.
+fn append_blocks<C: CompressorId>(compressor: &mut C, data: &[u8], block_size: usize, dest: &mut io::Write) -> io::Result<()> {
+    let mut lower_limit: usize = 0;
+    let num_bytes = data.len();
+
+    while lower_limit < num_bytes {
+        let upper_limit = cmp::min(lower_limit+block_size, num_bytes);
+        let chunk = &data[lower_limit..upper_limit];
+        let compressed_chunk = compressor.compress(chunk);
+        write_frame(dest, C::ID, compressed_chunk)?;
+
+        lower_limit = upper_limit;
+    }
+
+    Ok(())
+}
+
+// Same per-block framing as `append_blocks`, but with block boundaries chosen by a
+// `FastCdcChunker` instead of fixed offsets. `BlockDecompressor` doesn't need to know which
+// strategy picked the boundaries, since it only ever reads a length prefix and a CRC.
+fn append_blocks_cdc<C: CompressorId>(compressor: &mut C, data: &[u8], chunker: &FastCdcChunker, dest: &mut io::Write) -> io::Result<()> {
+    let mut lower_limit: usize = 0;
+    let num_bytes = data.len();
+
+    while lower_limit < num_bytes {
+        let chunk = chunker.next_chunk(&data[lower_limit..]);
+        let compressed_chunk = compressor.compress(chunk);
+        write_frame(dest, C::ID, compressed_chunk)?;
+
+        lower_limit += chunk.len();
+    }
+
+    Ok(())
+}
+
+struct BlockCompressor<C>
+    where C: CompressorId
 {
     compressor: C,
     block_size: usize
 }
 
 impl<C> BlockCompressor<C>
-    where C: Compressor
+    where C: CompressorId
 {
     fn new(compressor: C, block_size: usize) -> BlockCompressor<C> {
         BlockCompressor {
@@ -108,59 +693,71 @@ impl<C> BlockCompressor<C>
         }
     }
 
-    fn compress(&mut self, data: &[u8], dest: &mut io::Write) {
-        let mut lower_limit: usize = 0;
-        let num_bytes = data.len();
-
-        while lower_limit < num_bytes {
-            let upper_limit = cmp::min(lower_limit+self.block_size, num_bytes);
-            let chunk = &data[lower_limit..upper_limit];
-            let compressed_chunk = self.compressor.compress(chunk);
-            dest.write(compressed_chunk.len().to_raw_bytes()).unwrap();
-            dest.write(compressed_chunk).unwrap();
-
-            lower_limit = upper_limit;
-        }
+    fn compress(&mut self, data: &[u8], dest: &mut io::Write) -> io::Result<()> {
+        write_stream_header(dest)?;
+        append_blocks(&mut self.compressor, data, self.block_size, dest)
     }
 
-    fn get_block_decompressor<'a, 'b>(&'a mut self, reader: &'b mut Read) -> BlockDecompressor<'a, 'b, C> {
-        BlockDecompressor::new(&mut self.compressor, reader)
+    fn compress_cdc(&mut self, data: &[u8], dest: &mut io::Write, chunker: &FastCdcChunker) -> io::Result<()> {
+        write_stream_header(dest)?;
+        append_blocks_cdc(&mut self.compressor, data, chunker, dest)
     }
 }
 
 // -----------------------------------------------------------------------------------------------
-struct BlockDecompressor<'a, 'b, C> 
-    where C: Compressor + 'a
-{
-    compressor: &'a mut C,
+struct BlockDecompressor<'a, 'b> {
+    compressors: &'a mut CompressorList,
     reader: &'b mut Read,
     buffer: Vec<u8>
 }
 
-impl<'a, 'b, C> BlockDecompressor<'a, 'b, C>
-    where C: Compressor + 'a
-{
-    fn new(compressor: &'a mut C, reader: &'b mut Read) -> BlockDecompressor<'a, 'b, C> {
-        BlockDecompressor {
-            compressor: compressor,
+impl<'a, 'b> BlockDecompressor<'a, 'b> {
+    // Reads and validates the stream header (magic number + format version) before handing back a
+    // decompressor, so a foreign or incompatible file is rejected immediately instead of failing
+    // confusingly on the first block.
+    fn new(compressors: &'a mut CompressorList, reader: &'b mut Read) -> Result<BlockDecompressor<'a, 'b>, BlockError> {
+        let magic = read_u32(reader)?;
+        if magic != STREAM_MAGIC {
+            return Err(BlockError::InvalidMagic);
+        }
+
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != STREAM_VERSION {
+            return Err(BlockError::UnsupportedVersion(version[0]));
+        }
+
+        Ok(BlockDecompressor {
+            compressors: compressors,
             reader: reader,
             buffer: Vec::new()
-        }
+        })
     }
 
-    fn next_block<'c>(&'c mut self) -> Option<&'c [u8]> {
-        // Get the number of bytes
-        let mut chunk_size: usize = 0;
-        match self.reader.read(chunk_size.to_raw_bytes_mut()) {
-            Err(_) => return None,
-            Ok(0) => return None,
+    fn next_block<'c>(&'c mut self) -> Result<Option<&'c [u8]>, BlockError> {
+        // Get the codec id for this block
+        let mut codec_id = [0u8; 1];
+        match self.reader.read(&mut codec_id) {
+            Err(e) => return Err(BlockError::Io(e)),
+            Ok(0) => return Ok(None),
             _ => {}
         }
 
+        // Get the number of bytes and the CRC32C of the compressed payload
+        let mut chunk_size: usize = 0;
+        self.reader.read_exact(chunk_size.to_raw_bytes_mut())?;
+        let expected_crc = read_u32(self.reader)?;
+
         self.buffer.resize(chunk_size, 0);
-        self.reader.read(&mut self.buffer).unwrap();
-        let decompressed_data = self.compressor.decompress(&self.buffer);
-        Some(decompressed_data)
+        self.reader.read_exact(&mut self.buffer)?;
+
+        if crc32c(&self.buffer) != expected_crc {
+            return Err(BlockError::CrcMismatch);
+        }
+
+        let compressor = self.compressors.get_mut(codec_id[0]).ok_or(BlockError::UnknownCodec(codec_id[0]))?;
+        let decompressed_data = compressor.decompress(&self.buffer);
+        Ok(Some(decompressed_data))
     }
 }
 
@@ -217,6 +814,126 @@ impl ToRawBytes for usize
     }
 }
 
+// -----------------------------------------------------------------------------------------------
+// A bit-per-element validity bitmap (1 = present). Stored as plain bytes so it compresses well
+// under a general-purpose `Compressor` -- a column that's mostly null is mostly zero bits, which
+// run-compresses trivially.
+struct Bitmap {
+    bits: Vec<u8>,
+    len: usize
+}
+
+impl Bitmap {
+    fn new(len: usize) -> Bitmap {
+        Bitmap {
+            bits: vec![0u8; (len + 7) / 8],
+            len: len
+        }
+    }
+
+    fn set(&mut self, index: usize, value: bool) {
+        let byte = index / 8;
+        let bit = index % 8;
+        if value {
+            self.bits[byte] |= 1 << bit;
+        } else {
+            self.bits[byte] &= !(1 << bit);
+        }
+    }
+
+    fn get(&self, index: usize) -> bool {
+        let byte = index / 8;
+        let bit = index % 8;
+        (self.bits[byte] >> bit) & 1 == 1
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// Splits a column into a compressed validity bitmap and a densely-packed compressed array of just
+// the non-null values, instead of burning a sentinel value (`i32::MIN`/`i64::MIN`) to mark nulls.
+// The element count is prefixed onto `compressed_bitmap` since the bitmap's own byte length doesn't
+// round-trip it.
+fn encode_nullable<T, F, B, V>(values: &Vec<T>, is_null: F, bitmap_compressor: &mut B, values_compressor: &mut V) -> (Vec<u8>, Vec<u8>)
+    where T: Copy,
+          F: Fn(&T) -> bool,
+          B: Compressor,
+          V: Compressor
+{
+    let mut bitmap = Bitmap::new(values.len());
+    let mut present_values: Vec<T> = Vec::with_capacity(values.len());
+
+    for (i, value) in values.iter().enumerate() {
+        let valid = !is_null(value);
+        bitmap.set(i, valid);
+        if valid {
+            present_values.push(*value);
+        }
+    }
+
+    let mut compressed_bitmap = values.len().to_raw_bytes().to_vec();
+    compressed_bitmap.extend_from_slice(bitmap_compressor.compress(&bitmap.bits));
+
+    let compressed_values = values_compressor.compress(present_values.to_raw_bytes()).to_vec();
+
+    (compressed_bitmap, compressed_values)
+}
+
+// Gives the consumer the dense, present-only values with no per-element null check at all -- the
+// right shape for a workload like `do_test`'s sum, which never needed the null positions in the
+// first place.
+fn decode_nullable_values<'a, T, V>(compressed_values: &'a [u8], values_compressor: &'a mut V) -> &'a [T]
+    where V: Compressor
+{
+    let bytes = values_compressor.decompress(compressed_values);
+    unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / mem::size_of::<T>()) }
+}
+
+// Re-expands a nullable column back to one value per original element, using the bitmap to decide
+// where to splice in `null_value`. Returns `BlockError::TruncatedStream` rather than panicking if
+// `compressed_bitmap` is too short to hold a count, or if the bitmap and values streams disagree
+// on how many elements are present -- a corrupted file or a caller bug should surface as an `Err`,
+// not panic deep inside the iterator.
+fn decode_nullable_full<T, B, V>(compressed_bitmap: &[u8], compressed_values: &[u8], bitmap_compressor: &mut B, values_compressor: &mut V, null_value: T) -> Result<Vec<T>, BlockError>
+    where T: Copy,
+          B: Compressor,
+          V: Compressor
+{
+    if compressed_bitmap.len() < mem::size_of::<usize>() {
+        return Err(BlockError::TruncatedStream);
+    }
+
+    let mut count: usize = 0;
+    {
+        let count_slice = count.to_raw_bytes_mut();
+        count_slice.copy_from_slice(&compressed_bitmap[..count_slice.len()]);
+    }
+
+    let bitmap_bytes = bitmap_compressor.decompress(&compressed_bitmap[mem::size_of::<usize>()..]);
+    if bitmap_bytes.len() < (count + 7) / 8 {
+        return Err(BlockError::TruncatedStream);
+    }
+    let bitmap = Bitmap { bits: bitmap_bytes.to_vec(), len: count };
+
+    let present_values: &[T] = decode_nullable_values(compressed_values, values_compressor);
+    let mut present_iter = present_values.iter();
+
+    let mut result = Vec::with_capacity(count);
+    for i in 0..count {
+        if bitmap.get(i) {
+            let value = present_iter.next().ok_or(BlockError::TruncatedStream)?;
+            result.push(*value);
+        } else {
+            result.push(null_value);
+        }
+    }
+
+    if present_iter.next().is_some() {
+        return Err(BlockError::TruncatedStream);
+    }
+
+    Ok(result)
+}
+
 // -----------------------------------------------------------------------------------------------
 fn drop_caches() {
     fs::OpenOptions::new()
@@ -226,48 +943,166 @@ fn drop_caches() {
         .and_then(|mut f| f.write(b"3"))
         .unwrap();
 }
+
+// -----------------------------------------------------------------------------------------------
+fn new_compressor_list() -> CompressorList {
+    let mut list = CompressorList::new();
+    list.register(NoCompression::ID, Box::new(NoCompression::new()));
+    list.register(SnappyCompressor::ID, Box::new(SnappyCompressor::new()));
+    list.register(FsstCompressor::ID, Box::new(FsstCompressor::new()));
+    list.register(<IntegerCompressor<i32> as CompressorId>::ID, Box::new(IntegerCompressor::<i32>::new()));
+    list.register(<IntegerCompressor<i64> as CompressorId>::ID, Box::new(IntegerCompressor::<i64>::new()));
+    list
+}
+
 // -----------------------------------------------------------------------------------------------
-fn do_test<C: Compressor>(values: &Vec<i32>, compressor: C) {
+fn benchmark_decompression(file_name: &str, registry: &mut CompressorList) -> i64 {
+    let n = 20;
+    let times = (0..n).map(|_| {
+        let tic = time::now();
+
+        drop_caches();
+        let mut file = fs::File::open(file_name).unwrap();
+        let mut block_decompressor = BlockDecompressor::new(registry, &mut file).expect("invalid stream header");
+
+        let mut sum: i64 = 0;
+        while let Some(data) = block_decompressor.next_block().expect("corrupt block in stream") {
+            let values = unsafe { slice::from_raw_parts(data.as_ptr() as *const i32, data.len() / mem::size_of::<i32>()) };
+            sum += values.iter().filter(|&v| *v != i32::null_value()).fold(0i64, |ac, &v| ac + (v as i64));
+        }
+
+        let toc = time::now();
+        (toc-tic).num_milliseconds()
+    }).collect::<Vec<_>>();
+
+    times.iter().fold(0i64, |accum, &v| accum + v) / (times.len() as i64)
+}
+
+// -----------------------------------------------------------------------------------------------
+fn do_test<C: CompressorId>(values: &Vec<i32>, compressor: C) {
     let file_name = "/tmp/data.bin";
 
     let mut block_compressor = BlockCompressor::new(compressor, 256*1024);
     {
         let mut file = fs::File::create(file_name).unwrap();
-        block_compressor.compress(values.to_raw_bytes(), &mut file);
+        block_compressor.compress(values.to_raw_bytes(), &mut file).unwrap();
+    }
+
+    let mut registry = new_compressor_list();
+    let avg_time = benchmark_decompression(file_name, &mut registry);
+    println!("Avg. time: {} ms.", avg_time);
+}
+
+// -----------------------------------------------------------------------------------------------
+fn do_test_cdc<C: CompressorId>(values: &Vec<i32>, compressor: C) {
+    let file_name = "/tmp/data_cdc.bin";
+    let chunker = FastCdcChunker::new(256*1024, 64*1024, 1024*1024);
+
+    let data = values.to_raw_bytes();
+    let mut chunk_sizes: Vec<usize> = Vec::new();
+    {
+        let mut pos = 0;
+        while pos < data.len() {
+            let chunk = chunker.next_chunk(&data[pos..]);
+            chunk_sizes.push(chunk.len());
+            pos += chunk.len();
+        }
+    }
+
+    let avg_chunk_size = chunk_sizes.iter().fold(0usize, |a, &v| a+v) as f64 / chunk_sizes.len() as f64;
+    let variance = chunk_sizes.iter().fold(0f64, |a, &v| a + (v as f64 - avg_chunk_size).powi(2)) / chunk_sizes.len() as f64;
+    println!("FastCDC: {} chunks, avg size {:.0} bytes, stddev {:.0} bytes", chunk_sizes.len(), avg_chunk_size, variance.sqrt());
+
+    let mut block_compressor = BlockCompressor::new(compressor, 256*1024);
+    {
+        let mut file = fs::File::create(file_name).unwrap();
+        block_compressor.compress_cdc(data, &mut file, &chunker).unwrap();
     }
 
+    let mut registry = new_compressor_list();
+    let avg_time = benchmark_decompression(file_name, &mut registry);
+    println!("Avg. time: {} ms.", avg_time);
+}
+
+// -----------------------------------------------------------------------------------------------
+// Writes the two frames `encode_nullable` produces to `file_name` as a two-block stream in the
+// same self-describing, CRC32C-framed format `BlockCompressor`/`BlockDecompressor` use everywhere
+// else, instead of a one-off length-prefix scheme: `compressed_bitmap` and `compressed_values` are
+// already compressed, so each is wrapped in a single `NoCompression` block, sized so neither one
+// gets split.
+fn write_nullbitmap_stream(file_name: &str, compressed_bitmap: &[u8], compressed_values: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(file_name)?;
+    write_stream_header(&mut file)?;
+
+    let block_size = cmp::max(compressed_bitmap.len(), compressed_values.len()) + 1;
+    append_blocks(&mut NoCompression::new(), compressed_bitmap, block_size, &mut file)?;
+    append_blocks(&mut NoCompression::new(), compressed_values, block_size, &mut file)
+}
+
+fn read_nullbitmap_stream(reader: &mut Read) -> Result<(Vec<u8>, Vec<u8>), BlockError> {
+    let mut registry = CompressorList::new();
+    registry.register(NoCompression::ID, Box::new(NoCompression::new()));
+
+    let mut block_decompressor = BlockDecompressor::new(&mut registry, reader)?;
+    let compressed_bitmap = block_decompressor.next_block()?.ok_or(BlockError::TruncatedStream)?.to_vec();
+    let compressed_values = block_decompressor.next_block()?.ok_or(BlockError::TruncatedStream)?.to_vec();
+
+    Ok((compressed_bitmap, compressed_values))
+}
+
+// Benchmarks the null-bitmap encoding against the sentinel-value approach used by `do_test`, over
+// the same file-backed, cold-cache I/O path: (1) `decode_nullable_values`, the dense present-only
+// scan with no per-element null check, and (2) `decode_nullable_full`, which re-expands back to one
+// value per original element and is the fair comparison against `do_test`'s sentinel-filter scan.
+fn do_test_nullbitmap(values: &Vec<i32>) {
+    let file_name = "/tmp/data_nullbitmap.bin";
+
+    let mut bitmap_compressor = SnappyCompressor::new();
+    let mut values_compressor = SnappyCompressor::new();
+
+    let (compressed_bitmap, compressed_values) = encode_nullable(values, |v| *v == i32::null_value(), &mut bitmap_compressor, &mut values_compressor);
+    println!("Bitmap: {} bytes compressed, values: {} bytes compressed", compressed_bitmap.len(), compressed_values.len());
+
+    write_nullbitmap_stream(file_name, &compressed_bitmap, &compressed_values).unwrap();
 
     let n = 20;
-    let times = (0..n).map(|_| {
+
+    let dense_times = (0..n).map(|_| {
         let tic = time::now();
 
         drop_caches();
         let mut file = fs::File::open(file_name).unwrap();
-        let mut block_decompressor = block_compressor.get_block_decompressor(&mut file);
+        let (_, compressed_values) = read_nullbitmap_stream(&mut file).expect("corrupt null-bitmap stream");
 
-        let mut sum: i64 = 0;
-        while let Some(data) = block_decompressor.next_block() {
-            let values = unsafe { slice::from_raw_parts(data.as_ptr() as *const i32, data.len() / mem::size_of::<i32>()) };
-            sum += values.iter().filter(|&v| *v != i32::null_value()).fold(0i64, |ac, &v| ac + (v as i64));
-            /*{
-                let n = values.len();
-                for i in 0..n {
-                    let value = values[i];
-                    if value == i32::null_value() {
-                        continue;
-                    }
+        let present_values: &[i32] = decode_nullable_values(&compressed_values, &mut values_compressor);
+        let sum = present_values.iter().fold(0i64, |ac, &v| ac + (v as i64));
 
-                    sum += value as i64;
-                }
-            }*/
-        }
+        let toc = time::now();
+        let _ = sum;
+        (toc-tic).num_milliseconds()
+    }).collect::<Vec<_>>();
+
+    let avg_dense_time = dense_times.iter().fold(0i64, |accum, &v| accum + v) / (dense_times.len() as i64);
+    println!("Avg. time (null bitmap, dense scan, no per-element check): {} ms.", avg_dense_time);
+
+    let full_times = (0..n).map(|_| {
+        let tic = time::now();
+
+        drop_caches();
+        let mut file = fs::File::open(file_name).unwrap();
+        let (compressed_bitmap, compressed_values) = read_nullbitmap_stream(&mut file).expect("corrupt null-bitmap stream");
+
+        let full_values = decode_nullable_full(&compressed_bitmap, &compressed_values, &mut bitmap_compressor, &mut values_compressor, i32::null_value())
+            .expect("bitmap/values count mismatch");
+        let sum = full_values.iter().filter(|&v| *v != i32::null_value()).fold(0i64, |ac, &v| ac + (v as i64));
 
         let toc = time::now();
+        let _ = sum;
         (toc-tic).num_milliseconds()
     }).collect::<Vec<_>>();
 
-    let avg_time = times.iter().fold(0i64, |accum, &v| accum + v) / (times.len() as i64);
-    println!("Avg. time: {} ms.", avg_time);
+    let avg_full_time = full_times.iter().fold(0i64, |accum, &v| accum + v) / (full_times.len() as i64);
+    println!("Avg. time (null bitmap, full re-expand + sentinel filter): {} ms.", avg_full_time);
 }
 
 
@@ -286,5 +1121,241 @@ fn main() {
     do_test(&values, NoCompression::new());
     println!("Benchmarking with Snappy...");
     do_test(&values, SnappyCompressor::new());
+    println!("Benchmarking with FSST...");
+    do_test(&values, FsstCompressor::new());
+    println!("Benchmarking with delta+zigzag+StreamVByte (integer front-end)...");
+    do_test(&values, IntegerCompressor::<i32>::new());
+    println!("Benchmarking with FastCDC content-defined chunking + Snappy...");
+    do_test_cdc(&values, SnappyCompressor::new());
+    println!("Benchmarking with separate null bitmap...");
+    do_test_nullbitmap(&values);
+
+}
+
+// -----------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fsst_round_trip() {
+        let data = b"the quick brown fox jumps over the lazy dog the quick brown fox";
+
+        let mut compressor = FsstCompressor::new();
+        let compressed = compressor.compress(&data[..]).to_vec();
+
+        let mut decompressor = FsstCompressor::new();
+        let decompressed = decompressor.decompress(&compressed);
+
+        assert_eq!(&decompressed[..], &data[..]);
+    }
+
+    #[test]
+    fn fsst_decompress_empty_payload_does_not_panic() {
+        // Reachable through the block stream: a zero-length block passes the CRC check (crc32c of
+        // an empty slice is the fixed constant 0), so decompress() must handle it instead of
+        // indexing data[0] on an empty slice.
+        let mut decompressor = FsstCompressor::new();
+        let decompressed = decompressor.decompress(&[]);
+        assert_eq!(decompressed, &[] as &[u8]);
+    }
+
+    #[test]
+    fn fsst_prefers_one_byte_codes_for_a_dominant_single_byte() {
+        // Mostly-zero byte stream, as produced by `ToRawBytes` on a high-null-ratio i32 column: the
+        // trainer should pick the dominant byte as a 1-byte symbol, and `find_match` must be able to
+        // look it up (regression test for the bug where 1-byte symbols could never be matched).
+        let mut data = vec![0u8; 4000];
+        for i in (0..data.len()).step_by(37) {
+            data[i] = 7;
+        }
+
+        let mut compressor = FsstCompressor::new();
+        let compressed = compressor.compress(&data[..]).to_vec();
+
+        let escape_count = compressed.iter().filter(|&&b| b == FSST_ESCAPE).count();
+        assert!(
+            escape_count < data.len() / 10,
+            "expected most of the dominant byte to be coded as 1-byte symbols, got {} escapes",
+            escape_count
+        );
+
+        let mut decompressor = FsstCompressor::new();
+        let decompressed = decompressor.decompress(&compressed);
+        assert_eq!(&decompressed[..], &data[..]);
+    }
+
+    #[test]
+    fn integer_compressor_round_trip_i32() {
+        let values: Vec<i32> = vec![0, 1, 1, 2, 3, 5, 8, -13, -1000, 1_000_000, 0, 0];
+
+        let mut compressor = IntegerCompressor::<i32>::new();
+        let compressed = compressor.compress_values(&values).to_vec();
 
+        let mut decompressor = IntegerCompressor::<i32>::new();
+        let decompressed = decompressor.decompress_values(&compressed);
+
+        assert_eq!(decompressed, &values[..]);
+    }
+
+    #[test]
+    fn integer_compressor_round_trip_i64() {
+        let values: Vec<i64> = vec![0, -1, 1, i64::max_value(), i64::min_value(), 42, -42];
+
+        let mut compressor = IntegerCompressor::<i64>::new();
+        let compressed = compressor.compress_values(&values).to_vec();
+
+        let mut decompressor = IntegerCompressor::<i64>::new();
+        let decompressed = decompressor.decompress_values(&compressed);
+
+        assert_eq!(decompressed, &values[..]);
+    }
+
+    #[test]
+    fn fast_cdc_chunk_sizes_stay_within_bounds() {
+        let min_size = 64;
+        let max_size = 1024;
+        let chunker = FastCdcChunker::new(256, min_size, max_size);
+
+        let values: Vec<i32> = (0..50_000).collect();
+        let data = values.to_raw_bytes();
+
+        let mut pos = 0;
+        let mut chunk_count = 0;
+        while pos < data.len() {
+            let chunk = chunker.next_chunk(&data[pos..]);
+            assert!(chunk.len() > 0, "chunk must make progress");
+            assert!(chunk.len() <= max_size, "chunk of {} bytes exceeds max_size {}", chunk.len(), max_size);
+
+            let is_last_chunk = pos + chunk.len() == data.len();
+            if !is_last_chunk {
+                assert!(chunk.len() >= min_size, "non-final chunk of {} bytes is below min_size {}", chunk.len(), min_size);
+            }
+
+            pos += chunk.len();
+            chunk_count += 1;
+        }
+
+        assert!(chunk_count > 1, "expected the chunker to split this much data into more than one chunk");
+    }
+
+    #[test]
+    fn block_stream_cdc_round_trip() {
+        let values: Vec<i32> = (0..50_000).map(|i| i % 251).collect();
+        let data = values.to_raw_bytes();
+        let chunker = FastCdcChunker::new(1024, 256, 4096);
+
+        let mut stream = Vec::new();
+        {
+            let mut block_compressor = BlockCompressor::new(SnappyCompressor::new(), 8192);
+            block_compressor.compress_cdc(data, &mut stream, &chunker).unwrap();
+        }
+
+        let mut registry = new_compressor_list();
+        let mut decompressed = Vec::new();
+        {
+            let mut reader = &stream[..];
+            let mut block_decompressor = BlockDecompressor::new(&mut registry, &mut reader).unwrap();
+            while let Some(chunk) = block_decompressor.next_block().unwrap() {
+                decompressed.extend_from_slice(chunk);
+            }
+        }
+
+        assert_eq!(&decompressed[..], data);
+    }
+
+    #[test]
+    fn block_stream_round_trip_with_crc() {
+        let values: Vec<i32> = (0..10_000).map(|i| (i % 997) - 500).collect();
+        let data = values.to_raw_bytes();
+
+        let mut stream = Vec::new();
+        {
+            let mut block_compressor = BlockCompressor::new(SnappyCompressor::new(), 1024);
+            block_compressor.compress(data, &mut stream).unwrap();
+        }
+
+        let mut registry = new_compressor_list();
+        let mut decompressed = Vec::new();
+        {
+            let mut reader = &stream[..];
+            let mut block_decompressor = BlockDecompressor::new(&mut registry, &mut reader).unwrap();
+            while let Some(chunk) = block_decompressor.next_block().unwrap() {
+                decompressed.extend_from_slice(chunk);
+            }
+        }
+
+        assert_eq!(&decompressed[..], data);
+    }
+
+    #[test]
+    fn block_stream_mixes_codecs_within_one_stream() {
+        let first: Vec<i32> = (0..2_000).collect();
+        let second: Vec<i32> = (0..3_000).map(|i| -i).collect();
+        let first_data = first.to_raw_bytes();
+        let second_data = second.to_raw_bytes();
+
+        let mut stream = Vec::new();
+        write_stream_header(&mut stream).unwrap();
+        append_blocks(&mut NoCompression::new(), first_data, 512, &mut stream).unwrap();
+        append_blocks(&mut SnappyCompressor::new(), second_data, 512, &mut stream).unwrap();
+
+        let mut registry = new_compressor_list();
+        let mut decompressed = Vec::new();
+        {
+            let mut reader = &stream[..];
+            let mut block_decompressor = BlockDecompressor::new(&mut registry, &mut reader).unwrap();
+            while let Some(chunk) = block_decompressor.next_block().unwrap() {
+                decompressed.extend_from_slice(chunk);
+            }
+        }
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(first_data);
+        expected.extend_from_slice(second_data);
+        assert_eq!(&decompressed[..], &expected[..]);
+    }
+
+    #[test]
+    fn block_stream_detects_corrupted_payload() {
+        let values: Vec<i32> = vec![1, 2, 3, 4, 5];
+        let data = values.to_raw_bytes();
+
+        let mut stream = Vec::new();
+        {
+            let mut block_compressor = BlockCompressor::new(NoCompression::new(), 1024);
+            block_compressor.compress(data, &mut stream).unwrap();
+        }
+
+        // Flip a byte inside the first block's payload so its CRC32C no longer matches.
+        let payload_start = 4 /* magic */ + 1 /* version */ + 1 /* codec id */ + mem::size_of::<usize>() + 4 /* crc */;
+        stream[payload_start] ^= 0xff;
+
+        let mut registry = new_compressor_list();
+        let mut reader = &stream[..];
+        let mut block_decompressor = BlockDecompressor::new(&mut registry, &mut reader).unwrap();
+        match block_decompressor.next_block() {
+            Err(BlockError::CrcMismatch) => {}
+            other => panic!("expected BlockError::CrcMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_nullable_full_rejects_truncated_values_stream() {
+        let values: Vec<i32> = vec![1, i32::null_value(), 2, i32::null_value(), 3];
+
+        let mut bitmap_compressor = NoCompression::new();
+        let mut values_compressor = NoCompression::new();
+        let (compressed_bitmap, compressed_values) =
+            encode_nullable(&values, |v| *v == i32::null_value(), &mut bitmap_compressor, &mut values_compressor);
+
+        // Drop the last present value's bytes so the bitmap (which still claims 3 present values)
+        // disagrees with what `compressed_values` actually holds.
+        let truncated_values = &compressed_values[..compressed_values.len() - mem::size_of::<i32>()];
+
+        match decode_nullable_full(&compressed_bitmap, truncated_values, &mut bitmap_compressor, &mut values_compressor, i32::null_value()) {
+            Err(BlockError::TruncatedStream) => {}
+            other => panic!("expected BlockError::TruncatedStream, got {:?}", other),
+        }
+    }
 }